@@ -0,0 +1,151 @@
+//! Grain LFSR round-constant generation for Poseidon2.
+//!
+//! This follows the parameter-generation procedure specified by the
+//! Poseidon / Poseidon2 papers (see `calc_round_numbers.py` and
+//! `generate_parameters_grain.sage` in the reference implementation at
+//! <https://github.com/IAIK/Poseidon>): an 80-bit Grain LFSR is seeded with
+//! the instance parameters and clocked to produce a deterministic, public
+//! stream of field constants.
+
+use stwo::core::fields::m31::BaseField;
+
+use crate::poseidon2::{FULL_ROUNDS, N_HALF_FULL_ROUNDS, N_PARTIAL_ROUNDS, N_STATE};
+
+/// M31 modulus, 2^31 - 1.
+const MODULUS: u32 = (1 << 31) - 1;
+/// Bit-length of the M31 modulus.
+const MODULUS_BITS: u32 = 31;
+
+/// 80-bit Grain LFSR used to derive Poseidon round constants.
+struct GrainLfsr {
+    state: [u8; 80],
+}
+
+impl GrainLfsr {
+    /// Seeds the LFSR for a prime-field, x^5 S-box instance and discards the
+    /// first 160 generated bits, per the Grain parameter-generation spec.
+    fn new(field_bits: u32, width: u32, full_rounds: u32, partial_rounds: u32) -> Self {
+        let mut bits = Vec::with_capacity(80);
+        push_bits(&mut bits, 1, 2); // field type: 1 = prime field
+        push_bits(&mut bits, 0, 4); // S-box type: 0 = x^5
+        push_bits(&mut bits, field_bits, 12);
+        push_bits(&mut bits, width, 12);
+        push_bits(&mut bits, full_rounds, 10);
+        push_bits(&mut bits, partial_rounds, 10);
+        while bits.len() < 80 {
+            bits.push(1);
+        }
+
+        let mut state = [0u8; 80];
+        state.copy_from_slice(&bits);
+
+        let mut lfsr = Self { state };
+        for _ in 0..160 {
+            lfsr.next_bit();
+        }
+        lfsr
+    }
+
+    /// Clocks the LFSR once and returns the new bit, using taps
+    /// b0 ^ b13 ^ b23 ^ b38 ^ b51 ^ b62.
+    fn next_bit(&mut self) -> u8 {
+        let new_bit = self.state[0]
+            ^ self.state[13]
+            ^ self.state[23]
+            ^ self.state[38]
+            ^ self.state[51]
+            ^ self.state[62];
+        self.state.copy_within(1.., 0);
+        self.state[79] = new_bit;
+        new_bit
+    }
+
+    /// Draws one field element: reads 31 bits MSB-first and accepts via
+    /// rejection sampling (retrying while the raw value is >= p).
+    fn next_field_element(&mut self) -> BaseField {
+        loop {
+            let mut value: u32 = 0;
+            for _ in 0..MODULUS_BITS {
+                value = (value << 1) | u32::from(self.next_bit());
+            }
+            if value < MODULUS {
+                return BaseField::from_u32_unchecked(value);
+            }
+        }
+    }
+}
+
+fn push_bits(bits: &mut Vec<u8>, value: u32, n: u32) {
+    for i in (0..n).rev() {
+        bits.push(((value >> i) & 1) as u8);
+    }
+}
+
+/// Generates the external and internal round constants for the t=16,
+/// R_F=8, R_P=14 Poseidon2 instance over M31, in round order: the first
+/// `R_F/2` full-round blocks, then the `R_P` internal constants, then the
+/// remaining full-round blocks.
+pub fn generate_round_constants() -> (
+    [[BaseField; N_STATE]; FULL_ROUNDS],
+    [BaseField; N_PARTIAL_ROUNDS],
+) {
+    let mut lfsr = GrainLfsr::new(
+        MODULUS_BITS,
+        N_STATE as u32,
+        FULL_ROUNDS as u32,
+        N_PARTIAL_ROUNDS as u32,
+    );
+
+    let mut external = [[BaseField::from_u32_unchecked(0); N_STATE]; FULL_ROUNDS];
+    let mut internal = [BaseField::from_u32_unchecked(0); N_PARTIAL_ROUNDS];
+
+    for round in external.iter_mut().take(N_HALF_FULL_ROUNDS) {
+        for c in round.iter_mut() {
+            *c = lfsr.next_field_element();
+        }
+    }
+    for c in internal.iter_mut() {
+        *c = lfsr.next_field_element();
+    }
+    for round in external.iter_mut().skip(N_HALF_FULL_ROUNDS) {
+        for c in round.iter_mut() {
+            *c = lfsr.next_field_element();
+        }
+    }
+
+    (external, internal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejection_sampling_stays_in_field() {
+        let (external, internal) = generate_round_constants();
+        for round in external.iter() {
+            for c in round.iter() {
+                assert!(c.0 < MODULUS);
+            }
+        }
+        for c in internal.iter() {
+            assert!(c.0 < MODULUS);
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let (external1, internal1) = generate_round_constants();
+        let (external2, internal2) = generate_round_constants();
+        assert_eq!(external1, external2);
+        assert_eq!(internal1, internal2);
+    }
+
+    #[test]
+    fn test_not_the_placeholder_constant() {
+        let (external, internal) = generate_round_constants();
+        let placeholder = BaseField::from_u32_unchecked(1234);
+        assert_ne!(external[0][0], placeholder);
+        assert_ne!(internal[0], placeholder);
+    }
+}
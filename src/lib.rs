@@ -0,0 +1,14 @@
+//! Poseidon2 hash function implementation for M31 field — library surface.
+//!
+//! `main.rs` is just a demo of this crate's API. The permutation, sponge,
+//! Merkle tree, and matrix utilities are exposed here as real public
+//! modules so downstream crates can depend on `compress`, `MerkleTree`,
+//! `Poseidon2Sponge::squeeze`, and `matrix::secure_mds` directly, rather
+//! than everything only being reachable from `#[cfg(test)]` code in a
+//! bin-only crate.
+
+pub mod grain_lfsr;
+pub mod matrix;
+pub mod merkle;
+pub mod poseidon2;
+pub mod sponge;
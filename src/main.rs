@@ -1,7 +1,5 @@
-mod poseidon2;
-mod sponge;
-
-use sponge::{hash_messages, Poseidon2Sponge};
+use poseidon2_m31::poseidon2::{Width16Spec, N_STATE, RATE};
+use poseidon2_m31::sponge::{hash_messages, Poseidon2Sponge};
 use stwo::core::fields::m31::BaseField;
 
 fn main() {
@@ -9,7 +7,7 @@ fn main() {
 
     // Example 1: Auto-padding
     println!("Example 1: Auto-padding");
-    let mut sponge = Poseidon2Sponge::new();
+    let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, RATE>::new();
     sponge.absorb(BaseField::from_u32_unchecked(1));
     sponge.absorb(BaseField::from_u32_unchecked(2));
     sponge.absorb(BaseField::from_u32_unchecked(3));
@@ -23,8 +21,7 @@ fn main() {
     let message = std::array::from_fn(|i| BaseField::from_u32_unchecked(i as u32));
     let outputs = hash_messages(&[message]);
     println!("Input:    [0, 1, 2, 3, 4, 5, 6, 7]");
-    println!("Hash:     {}", outputs[0][0].0);
-    println!("Expected: 334078718 ✓\n");
+    println!("Hash:     {}\n", outputs[0][0].0);
 
     // Example 3: Vertical chaining
     println!("Example 3: Vertical chaining (3 messages)");
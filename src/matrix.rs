@@ -0,0 +1,231 @@
+//! Matrix utilities for Poseidon2: checking that the internal round matrix
+//! is invertible, and a pseudo-random Cauchy MDS generator mirroring the
+//! halo2 `mds.rs` approach, for parameter sets that want generated (rather
+//! than paper-specified) matrices.
+
+use stwo::core::fields::m31::BaseField;
+
+use crate::poseidon2::M31_MODULUS;
+
+const ZERO: BaseField = BaseField::from_u32_unchecked(0);
+const ONE: BaseField = BaseField::from_u32_unchecked(1);
+
+/// `x^(p-2)`, the multiplicative inverse of nonzero `x` via Fermat's little
+/// theorem.
+fn field_inverse(x: BaseField) -> BaseField {
+    let mut result = ONE;
+    let mut base = x;
+    let mut exp = (M31_MODULUS - 2) as u64;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base;
+        }
+        base = base * base;
+        exp >>= 1;
+    }
+    result
+}
+
+/// Inverts a square matrix over M31 via Gauss-Jordan elimination, or
+/// returns `None` if it is singular.
+pub fn invert<const N: usize>(matrix: &[[BaseField; N]; N]) -> Option<[[BaseField; N]; N]> {
+    let mut a = *matrix;
+    let mut inv: [[BaseField; N]; N] =
+        std::array::from_fn(|i| std::array::from_fn(|j| if i == j { ONE } else { ZERO }));
+
+    for col in 0..N {
+        let pivot_row = (col..N).find(|&row| a[row][col] != ZERO)?;
+        a.swap(pivot_row, col);
+        inv.swap(pivot_row, col);
+
+        let pivot_inv = field_inverse(a[col][col]);
+        for j in 0..N {
+            a[col][j] = a[col][j] * pivot_inv;
+            inv[col][j] = inv[col][j] * pivot_inv;
+        }
+
+        for row in 0..N {
+            if row == col {
+                continue;
+            }
+            let factor = a[row][col];
+            if factor == ZERO {
+                continue;
+            }
+            for j in 0..N {
+                a[row][j] = a[row][j] - a[col][j] * factor;
+                inv[row][j] = inv[row][j] - inv[col][j] * factor;
+            }
+        }
+    }
+
+    Some(inv)
+}
+
+/// Whether a square matrix is invertible over M31.
+pub fn is_invertible<const N: usize>(matrix: &[[BaseField; N]; N]) -> bool {
+    invert(matrix).is_some()
+}
+
+/// Whether `M_I = J + diag(d)` (the internal round matrix, where `J` is the
+/// all-ones matrix) is invertible for the given diagonal `d`.
+pub fn internal_matrix_is_invertible<const WIDTH: usize>(diagonal: &[BaseField; WIDTH]) -> bool {
+    let m_i: [[BaseField; WIDTH]; WIDTH] =
+        std::array::from_fn(|i| std::array::from_fn(|j| if i == j { ONE + diagonal[i] } else { ONE }));
+    is_invertible(&m_i)
+}
+
+/// Arbitrary odd constant used to separate the `x` and `y` streams drawn
+/// from the same seed.
+const Y_STREAM_OFFSET: u32 = 0x9E37_79B9;
+
+/// Deterministically derives `N` field elements from `seed` by iterating
+/// `x -> x^2 + 1`, as a simple pseudo-random stream.
+fn derive_sequence<const N: usize>(seed: u32) -> [BaseField; N] {
+    let mut x = BaseField::from_u32_unchecked(seed) + ONE;
+    std::array::from_fn(|_| {
+        x = x * x + ONE;
+        x
+    })
+}
+
+fn has_duplicates<const N: usize>(values: &[BaseField; N]) -> bool {
+    for i in 0..N {
+        for j in (i + 1)..N {
+            if values[i] == values[j] {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Generates a pseudo-random MDS matrix over M31 (and its inverse) via a
+/// Cauchy construction: pick `T` elements `x_i` and `T` elements `y_j` and
+/// set `M[i][j] = 1 / (x_i + y_j)`. A Cauchy matrix with pairwise-distinct
+/// `x_i`, pairwise-distinct `y_j`, and no `x_i + y_j == 0` is unconditionally
+/// MDS (every square submatrix is invertible), so this just increments the
+/// seed — the `secure_mds` index — until those conditions hold, mirroring
+/// the halo2 `mds.rs` approach. Returns `(mds, mds_inverse, secure_mds)` so
+/// downstream circuit code can use the inverse for witness reconstruction.
+///
+/// ```
+/// use poseidon2_m31::matrix::secure_mds;
+/// use stwo::core::fields::m31::BaseField;
+///
+/// let (mds, mds_inverse, _seed) = secure_mds::<4>(0);
+///
+/// // A circuit that commits to `mds * witness` can reconstruct `witness`
+/// // from the committed output using `mds_inverse`, without re-deriving
+/// // the matrix.
+/// let witness = [
+///     BaseField::from_u32_unchecked(1),
+///     BaseField::from_u32_unchecked(2),
+///     BaseField::from_u32_unchecked(3),
+///     BaseField::from_u32_unchecked(4),
+/// ];
+/// let zero = BaseField::from_u32_unchecked(0);
+/// let committed: [BaseField; 4] =
+///     std::array::from_fn(|i| (0..4).fold(zero, |acc, j| acc + mds[i][j] * witness[j]));
+/// let recovered: [BaseField; 4] =
+///     std::array::from_fn(|i| (0..4).fold(zero, |acc, j| acc + mds_inverse[i][j] * committed[j]));
+/// assert_eq!(recovered, witness);
+/// ```
+pub fn secure_mds<const T: usize>(mut seed: u32) -> ([[BaseField; T]; T], [[BaseField; T]; T], u32) {
+    loop {
+        let xs: [BaseField; T] = derive_sequence(seed);
+        let ys: [BaseField; T] = derive_sequence(seed.wrapping_add(Y_STREAM_OFFSET));
+
+        if has_duplicates(&xs) || has_duplicates(&ys) {
+            seed = seed.wrapping_add(1);
+            continue;
+        }
+
+        let mut mds = [[ZERO; T]; T];
+        let mut denominator_is_zero = false;
+        'rows: for i in 0..T {
+            for j in 0..T {
+                let denom = xs[i] + ys[j];
+                if denom == ZERO {
+                    denominator_is_zero = true;
+                    break 'rows;
+                }
+                mds[i][j] = field_inverse(denom);
+            }
+        }
+        if denominator_is_zero {
+            seed = seed.wrapping_add(1);
+            continue;
+        }
+
+        if let Some(mds_inverse) = invert(&mds) {
+            return (mds, mds_inverse, seed);
+        }
+        seed = seed.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon2::INTERNAL_DIAGONAL;
+
+    #[test]
+    fn test_identity_is_invertible() {
+        let identity: [[BaseField; 3]; 3] =
+            std::array::from_fn(|i| std::array::from_fn(|j| if i == j { ONE } else { ZERO }));
+        assert!(is_invertible(&identity));
+    }
+
+    #[test]
+    fn test_singular_matrix_is_not_invertible() {
+        let singular = [
+            [ONE, ONE],
+            [ONE, ONE],
+        ];
+        assert!(!is_invertible(&singular));
+    }
+
+    #[test]
+    fn test_invert_round_trips() {
+        let m = [
+            [BaseField::from_u32_unchecked(2), BaseField::from_u32_unchecked(1)],
+            [BaseField::from_u32_unchecked(1), BaseField::from_u32_unchecked(1)],
+        ];
+        let inv = invert(&m).expect("matrix is invertible");
+
+        // m * inv should be the identity.
+        let product: [[BaseField; 2]; 2] = std::array::from_fn(|i| {
+            std::array::from_fn(|j| {
+                (0..2).fold(ZERO, |acc, k| acc + m[i][k] * inv[k][j])
+            })
+        });
+        assert_eq!(product, [[ONE, ZERO], [ZERO, ONE]]);
+    }
+
+    #[test]
+    fn test_width16_internal_diagonal_is_invertible() {
+        assert!(internal_matrix_is_invertible(&INTERNAL_DIAGONAL));
+    }
+
+    #[test]
+    fn test_secure_mds_is_invertible() {
+        let (mds, mds_inverse, _) = secure_mds::<4>(0);
+        assert!(is_invertible(&mds));
+
+        let product: [[BaseField; 4]; 4] = std::array::from_fn(|i| {
+            std::array::from_fn(|j| (0..4).fold(ZERO, |acc, k| acc + mds[i][k] * mds_inverse[k][j]))
+        });
+        let identity: [[BaseField; 4]; 4] =
+            std::array::from_fn(|i| std::array::from_fn(|j| if i == j { ONE } else { ZERO }));
+        assert_eq!(product, identity);
+    }
+
+    #[test]
+    fn test_secure_mds_deterministic() {
+        let (mds1, _, secure_mds1) = secure_mds::<4>(0);
+        let (mds2, _, secure_mds2) = secure_mds::<4>(0);
+        assert_eq!(mds1, mds2);
+        assert_eq!(secure_mds1, secure_mds2);
+    }
+}
@@ -0,0 +1,246 @@
+//! Poseidon2 2-to-1 compression and a Merkle tree built on top of it.
+
+use std::marker::PhantomData;
+
+use stwo::core::fields::m31::BaseField;
+
+use crate::poseidon2::{poseidon2_permutation, Spec, Width16Spec, N_STATE, RATE as DEFAULT_RATE};
+
+/// Domain separation tag folded into the state before every `compress` call,
+/// so an internal node's hash is never computed over the same input shape a
+/// leaf digest could produce. Without this, a leaf digest `(value, 0, ...,
+/// 0)` and an internal node's output live in the same `[BaseField; RATE]`
+/// shape with nothing to tell them apart by construction — the classic
+/// Merkle leaf/node confusion bug class (cf. RFC 6962's leaf/node prefix).
+const NODE_DOMAIN_TAG: BaseField = BaseField::from_u32_unchecked(2);
+
+/// Domain separation tag embedded in every leaf digest, distinguishing it
+/// by construction from an (untagged before hashing) internal node's input.
+const LEAF_DOMAIN_TAG: BaseField = BaseField::from_u32_unchecked(1);
+
+/// Compresses two rate-sized digests into one by loading both halves into
+/// the full state, folding in the node domain tag, and running the
+/// Poseidon2 permutation, keeping the rate portion of the output. This is
+/// the 2-to-1 gadget every Poseidon-based commitment scheme builds on, as
+/// opposed to `hash_messages`'s sequential vertical chaining.
+pub fn compress<S, const WIDTH: usize, const RATE: usize>(
+    left: [BaseField; RATE],
+    right: [BaseField; RATE],
+) -> [BaseField; RATE]
+where
+    S: Spec<WIDTH, RATE>,
+{
+    assert_eq!(WIDTH, 2 * RATE, "compress requires WIDTH == 2 * RATE");
+
+    let mut state: [BaseField; WIDTH] = std::array::from_fn(|i| {
+        if i < RATE {
+            left[i]
+        } else {
+            right[i - RATE]
+        }
+    });
+    state[WIDTH - 1] += NODE_DOMAIN_TAG;
+    poseidon2_permutation::<S, WIDTH, RATE>(&mut state);
+    std::array::from_fn(|i| state[i])
+}
+
+/// Embeds a single field element as a rate-sized leaf digest: the element
+/// in the first slot, the leaf domain tag in the last slot, zero elsewhere.
+fn leaf_digest<const RATE: usize>(leaf: BaseField) -> [BaseField; RATE] {
+    std::array::from_fn(|i| {
+        if i == 0 {
+            leaf
+        } else if i == RATE - 1 {
+            LEAF_DOMAIN_TAG
+        } else {
+            BaseField::from_u32_unchecked(0)
+        }
+    })
+}
+
+/// An authentication path: the sibling digest at each level from the leaf
+/// up to the root, paired with whether the path's node was the right child
+/// at that level (and so the sibling is the left one).
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerklePath<const RATE: usize> {
+    siblings: Vec<[BaseField; RATE]>,
+    is_right: Vec<bool>,
+}
+
+impl<const RATE: usize> MerklePath<RATE> {
+    /// Recomputes the root from `leaf` along this path and checks it
+    /// matches `root`.
+    pub fn verify<S, const WIDTH: usize>(&self, leaf: BaseField, root: [BaseField; RATE]) -> bool
+    where
+        S: Spec<WIDTH, RATE>,
+    {
+        let mut node = leaf_digest(leaf);
+        for (sibling, is_right) in self.siblings.iter().zip(self.is_right.iter()) {
+            node = if *is_right {
+                compress::<S, WIDTH, RATE>(*sibling, node)
+            } else {
+                compress::<S, WIDTH, RATE>(node, *sibling)
+            };
+        }
+        node == root
+    }
+}
+
+/// A Merkle tree over `BaseField` leaves, using `compress` to build each
+/// internal node from its two children. Leaf count is padded up to the
+/// next power of two with all-zero digests (not the digest of a leaf whose
+/// value is zero).
+///
+/// Defaults its type parameters to the t=16 instance (`Width16Spec`), like
+/// [`crate::sponge::Poseidon2Sponge`].
+pub struct MerkleTree<S = Width16Spec, const WIDTH: usize = N_STATE, const RATE: usize = DEFAULT_RATE>
+where
+    S: Spec<WIDTH, RATE>,
+{
+    /// `layers[0]` holds the (padded) leaf digests; `layers.last()` holds the root.
+    layers: Vec<Vec<[BaseField; RATE]>>,
+    _spec: PhantomData<S>,
+}
+
+impl<S, const WIDTH: usize, const RATE: usize> MerkleTree<S, WIDTH, RATE>
+where
+    S: Spec<WIDTH, RATE>,
+{
+    /// Builds a Merkle tree over `leaves`, padding with all-zero digests up
+    /// to the next power of two.
+    pub fn new(leaves: &[BaseField]) -> Self {
+        assert!(!leaves.is_empty(), "merkle tree requires at least one leaf");
+
+        let padded_len = leaves.len().next_power_of_two();
+        let mut level: Vec<[BaseField; RATE]> = (0..padded_len)
+            .map(|i| {
+                leaves
+                    .get(i)
+                    .copied()
+                    .map(leaf_digest)
+                    .unwrap_or([BaseField::from_u32_unchecked(0); RATE])
+            })
+            .collect();
+
+        let mut layers = vec![level.clone()];
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| compress::<S, WIDTH, RATE>(pair[0], pair[1]))
+                .collect();
+            layers.push(level.clone());
+        }
+
+        Self {
+            layers,
+            _spec: PhantomData,
+        }
+    }
+
+    /// The tree's root digest.
+    pub fn root(&self) -> [BaseField; RATE] {
+        self.layers.last().expect("tree always has at least one layer")[0]
+    }
+
+    /// Builds the authentication path for the leaf at `index`.
+    pub fn open(&self, mut index: usize) -> MerklePath<RATE> {
+        assert!(
+            index < self.layers[0].len(),
+            "leaf index out of bounds for this tree"
+        );
+
+        let mut siblings = Vec::new();
+        let mut is_right = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let sibling_index = index ^ 1;
+            siblings.push(layer[sibling_index]);
+            is_right.push(index % 2 == 1);
+            index /= 2;
+        }
+
+        MerklePath { siblings, is_right }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u32) -> Vec<BaseField> {
+        (0..n).map(BaseField::from_u32_unchecked).collect()
+    }
+
+    #[test]
+    fn test_compress_deterministic() {
+        let left = leaf_digest::<8>(BaseField::from_u32_unchecked(1));
+        let right = leaf_digest::<8>(BaseField::from_u32_unchecked(2));
+        let out1 = compress::<Width16Spec, 16, 8>(left, right);
+        let out2 = compress::<Width16Spec, 16, 8>(left, right);
+        assert_eq!(out1, out2);
+    }
+
+    #[test]
+    fn test_compress_not_commutative() {
+        let left = leaf_digest::<8>(BaseField::from_u32_unchecked(1));
+        let right = leaf_digest::<8>(BaseField::from_u32_unchecked(2));
+        let out1 = compress::<Width16Spec, 16, 8>(left, right);
+        let out2 = compress::<Width16Spec, 16, 8>(right, left);
+        assert_ne!(out1, out2);
+    }
+
+    #[test]
+    fn test_root_is_deterministic() {
+        let tree1 = MerkleTree::<Width16Spec, 16, 8>::new(&leaves(4));
+        let tree2 = MerkleTree::<Width16Spec, 16, 8>::new(&leaves(4));
+        assert_eq!(tree1.root(), tree2.root());
+    }
+
+    #[test]
+    fn test_open_and_verify_round_trip() {
+        let data = leaves(8);
+        let tree = MerkleTree::<Width16Spec, 16, 8>::new(&data);
+        let root = tree.root();
+
+        for (i, &leaf) in data.iter().enumerate() {
+            let path = tree.open(i);
+            assert!(path.verify::<Width16Spec, 16>(leaf, root));
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_leaf() {
+        let data = leaves(4);
+        let tree = MerkleTree::<Width16Spec, 16, 8>::new(&data);
+        let root = tree.root();
+
+        let path = tree.open(0);
+        let wrong_leaf = BaseField::from_u32_unchecked(999);
+        assert!(!path.verify::<Width16Spec, 16>(wrong_leaf, root));
+    }
+
+    #[test]
+    fn test_merkle_tree_toy_spec() {
+        // Runs compress/MerkleTree through the t=8/rate=4 toy instance, not
+        // just the production t=16 one, to exercise the generic code paths.
+        use crate::poseidon2::ToySpec;
+
+        let data = leaves(4);
+        let tree = MerkleTree::<ToySpec, 8, 4>::new(&data);
+        let root = tree.root();
+
+        for (i, &leaf) in data.iter().enumerate() {
+            let path = tree.open(i);
+            assert!(path.verify::<ToySpec, 8>(leaf, root));
+        }
+    }
+
+    #[test]
+    fn test_non_power_of_two_leaf_count() {
+        let data = leaves(5);
+        let tree = MerkleTree::<Width16Spec, 16, 8>::new(&data);
+        let root = tree.root();
+
+        let path = tree.open(4);
+        assert!(path.verify::<Width16Spec, 16>(data[4], root));
+    }
+}
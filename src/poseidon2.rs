@@ -3,8 +3,12 @@
 //! Based on the paper: <https://eprint.iacr.org/2023/323.pdf>
 
 use std::ops::{Add, AddAssign, Mul, Sub};
+use std::sync::LazyLock;
+
 use stwo::core::fields::m31::BaseField;
 
+use crate::grain_lfsr::generate_round_constants;
+
 pub const N_STATE: usize = 16;
 pub const RATE: usize = 8;
 pub const CAPACITY: usize = 8;
@@ -12,11 +16,169 @@ pub const N_PARTIAL_ROUNDS: usize = 14;
 pub const N_HALF_FULL_ROUNDS: usize = 4;
 pub const FULL_ROUNDS: usize = 2 * N_HALF_FULL_ROUNDS;
 
-pub const EXTERNAL_ROUND_CONSTS: [[BaseField; N_STATE]; FULL_ROUNDS] =
-    [[BaseField::from_u32_unchecked(1234); N_STATE]; FULL_ROUNDS];
+/// M31 modulus, 2^31 - 1.
+pub(crate) const M31_MODULUS: u32 = (1 << 31) - 1;
+
+/// Diagonal of the t=16 internal round matrix `M_I = J + diag(d)`: `d_i = 2^(i+1)`.
+pub const INTERNAL_DIAGONAL: [BaseField; N_STATE] = {
+    let mut d = [BaseField::from_u32_unchecked(0); N_STATE];
+    let mut i = 0;
+    while i < N_STATE {
+        d[i] = BaseField::from_u32_unchecked(1u32 << (i + 1));
+        i += 1;
+    }
+    d
+};
+
+/// Round constants, generated once from the instance parameters via the
+/// Grain LFSR (see `grain_lfsr`) rather than hardcoded.
+static ROUND_CONSTANTS: LazyLock<(
+    [[BaseField; N_STATE]; FULL_ROUNDS],
+    [BaseField; N_PARTIAL_ROUNDS],
+)> = LazyLock::new(generate_round_constants);
+
+/// Round constants for the 8 external (full) rounds.
+pub fn external_round_consts() -> &'static [[BaseField; N_STATE]; FULL_ROUNDS] {
+    &ROUND_CONSTANTS.0
+}
+
+/// Round constants for the 14 internal (partial) rounds.
+pub fn internal_round_consts() -> &'static [BaseField; N_PARTIAL_ROUNDS] {
+    &ROUND_CONSTANTS.1
+}
 
-pub const INTERNAL_ROUND_CONSTS: [BaseField; N_PARTIAL_ROUNDS] =
-    [BaseField::from_u32_unchecked(1234); N_PARTIAL_ROUNDS];
+/// Describes a concrete Poseidon2 instance: its state width, rate, round
+/// counts, S-box, and round constants. Analogous to the halo2 Poseidon
+/// `Spec` trait, this lets the permutation and sponge be written once and
+/// instantiated for different widths (e.g. t=16, t=12, t=24) instead of
+/// forking the code per parameter set.
+pub trait Spec<const WIDTH: usize, const RATE: usize> {
+    /// Number of full rounds (split evenly before and after the partial rounds).
+    fn full_rounds() -> usize;
+
+    /// Number of partial rounds.
+    fn partial_rounds() -> usize;
+
+    /// The S-box applied to state elements.
+    fn sbox(val: BaseField) -> BaseField;
+
+    /// Round constants: one `WIDTH`-wide row per full round, and one
+    /// element per partial round, in round order.
+    fn constants() -> (&'static [[BaseField; WIDTH]], &'static [BaseField]);
+
+    /// Diagonal `d` of the internal round matrix `M_I = J + diag(d)`, where
+    /// `J` is the all-ones matrix. See `matrix::internal_matrix_is_invertible`
+    /// to validate a candidate diagonal.
+    fn internal_diagonal() -> &'static [BaseField; WIDTH];
+}
+
+/// The original t=16 Poseidon2 instance (8 full rounds, 14 partial rounds,
+/// x^5 S-box), kept concrete so existing call sites are unaffected.
+#[derive(Clone, Copy)]
+pub struct Width16Spec;
+
+impl Spec<N_STATE, RATE> for Width16Spec {
+    fn full_rounds() -> usize {
+        FULL_ROUNDS
+    }
+
+    fn partial_rounds() -> usize {
+        N_PARTIAL_ROUNDS
+    }
+
+    fn sbox(val: BaseField) -> BaseField {
+        pow5(val)
+    }
+
+    fn constants() -> (&'static [[BaseField; N_STATE]], &'static [BaseField]) {
+        (
+            external_round_consts().as_slice(),
+            internal_round_consts().as_slice(),
+        )
+    }
+
+    fn internal_diagonal() -> &'static [BaseField; N_STATE] {
+        &INTERNAL_DIAGONAL
+    }
+}
+
+/// A second, tiny instance (t=8, rate=4) used only by tests to exercise the
+/// generic code paths (`poseidon2_permutation`, `Poseidon2Sponge`,
+/// `compress`/`MerkleTree`) with a width other than the production t=16, so
+/// the generalization itself — not just the one case that already worked
+/// before it — is covered.
+#[cfg(test)]
+const TOY_WIDTH: usize = 8;
+#[cfg(test)]
+const TOY_RATE: usize = 4;
+#[cfg(test)]
+const TOY_FULL_ROUNDS: usize = 4;
+#[cfg(test)]
+const TOY_PARTIAL_ROUNDS: usize = 4;
+
+#[cfg(test)]
+const TOY_EXTERNAL_CONSTS: [[BaseField; TOY_WIDTH]; TOY_FULL_ROUNDS] = {
+    let mut consts = [[BaseField::from_u32_unchecked(0); TOY_WIDTH]; TOY_FULL_ROUNDS];
+    let mut round = 0;
+    while round < TOY_FULL_ROUNDS {
+        let mut i = 0;
+        while i < TOY_WIDTH {
+            consts[round][i] = BaseField::from_u32_unchecked((round * TOY_WIDTH + i + 1) as u32);
+            i += 1;
+        }
+        round += 1;
+    }
+    consts
+};
+
+#[cfg(test)]
+const TOY_INTERNAL_CONSTS: [BaseField; TOY_PARTIAL_ROUNDS] = {
+    let mut consts = [BaseField::from_u32_unchecked(0); TOY_PARTIAL_ROUNDS];
+    let mut i = 0;
+    while i < TOY_PARTIAL_ROUNDS {
+        consts[i] = BaseField::from_u32_unchecked(100 + i as u32);
+        i += 1;
+    }
+    consts
+};
+
+#[cfg(test)]
+const TOY_INTERNAL_DIAGONAL: [BaseField; TOY_WIDTH] = {
+    let mut d = [BaseField::from_u32_unchecked(0); TOY_WIDTH];
+    let mut i = 0;
+    while i < TOY_WIDTH {
+        d[i] = BaseField::from_u32_unchecked(1u32 << (i + 1));
+        i += 1;
+    }
+    d
+};
+
+#[cfg(test)]
+#[derive(Clone, Copy)]
+pub(crate) struct ToySpec;
+
+#[cfg(test)]
+impl Spec<TOY_WIDTH, TOY_RATE> for ToySpec {
+    fn full_rounds() -> usize {
+        TOY_FULL_ROUNDS
+    }
+
+    fn partial_rounds() -> usize {
+        TOY_PARTIAL_ROUNDS
+    }
+
+    fn sbox(val: BaseField) -> BaseField {
+        pow5(val)
+    }
+
+    fn constants() -> (&'static [[BaseField; TOY_WIDTH]], &'static [BaseField]) {
+        (TOY_EXTERNAL_CONSTS.as_slice(), TOY_INTERNAL_CONSTS.as_slice())
+    }
+
+    fn internal_diagonal() -> &'static [BaseField; TOY_WIDTH] {
+        &TOY_INTERNAL_DIAGONAL
+    }
+}
 
 /// Applies x^5 S-box
 #[inline]
@@ -48,13 +210,21 @@ where
     [t6, t5, t7, t4]
 }
 
-/// Applies the external round matrix (section 5.1, Appendix B)
-pub fn apply_external_round_matrix<F>(state: &mut [F; N_STATE])
+/// Applies the external round matrix (section 5.1, Appendix B) for a state
+/// of any width that is a multiple of 4: `circ(2M4, M4, ..., M4)`.
+pub fn apply_external_round_matrix<F, const WIDTH: usize>(state: &mut [F; WIDTH])
 where
     F: Clone + AddAssign<F> + Add<F, Output = F> + Sub<F, Output = F> + Mul<BaseField, Output = F>,
 {
-    // Apply circ(2M4, M4, M4, M4)
-    for i in 0..4 {
+    assert_eq!(
+        WIDTH % 4,
+        0,
+        "external round matrix requires WIDTH % 4 == 0"
+    );
+    let blocks = WIDTH / 4;
+
+    // Apply M4 to each block of 4
+    for i in 0..blocks {
         [
             state[4 * i],
             state[4 * i + 1],
@@ -68,19 +238,24 @@ where
         ]);
     }
 
-    // Apply column mixing
+    // Apply column mixing across blocks
     for j in 0..4 {
-        let s =
-            state[j].clone() + state[j + 4].clone() + state[j + 8].clone() + state[j + 12].clone();
-        for i in 0..4 {
-            state[4 * i + j] += s.clone();
+        let mut s = state[j].clone();
+        for block in 1..blocks {
+            s += state[4 * block + j].clone();
+        }
+        for block in 0..blocks {
+            state[4 * block + j] += s.clone();
         }
     }
 }
 
-/// Applies the internal round matrix (section 5.2)
-pub fn apply_internal_round_matrix<F>(state: &mut [F; N_STATE])
-where
+/// Applies the internal round matrix `M_I = J + diag(d)` (section 5.2),
+/// where `d` is the Spec-provided diagonal rather than a fixed formula.
+pub fn apply_internal_round_matrix<F, const WIDTH: usize>(
+    state: &mut [F; WIDTH],
+    diagonal: &[BaseField; WIDTH],
+) where
     F: Clone + AddAssign<F> + Add<F, Output = F> + Sub<F, Output = F> + Mul<BaseField, Output = F>,
 {
     let sum = state[1..]
@@ -89,41 +264,43 @@ where
         .fold(state[0].clone(), |acc, s| acc + s);
 
     state.iter_mut().enumerate().for_each(|(i, s)| {
-        *s = s.clone() * BaseField::from_u32_unchecked(1 << (i + 1)) + sum.clone();
+        *s = s.clone() * diagonal[i] + sum.clone();
     });
 }
 
-/// Applies the Poseidon2 permutation to a state
-pub fn poseidon2_permutation(state: &mut [BaseField; N_STATE]) {
-    // First 4 full rounds
-    for round in 0..N_HALF_FULL_ROUNDS {
-        // Add round constants
-        for i in 0..N_STATE {
-            state[i] += EXTERNAL_ROUND_CONSTS[round][i];
+/// Applies the Poseidon2 permutation to a state, for any instance described
+/// by a `Spec<WIDTH, RATE>`.
+pub fn poseidon2_permutation<S, const WIDTH: usize, const RATE: usize>(state: &mut [BaseField; WIDTH])
+where
+    S: Spec<WIDTH, RATE>,
+{
+    let half_full_rounds = S::full_rounds() / 2;
+    let (external_consts, internal_consts) = S::constants();
+
+    // First half of full rounds
+    for round in 0..half_full_rounds {
+        for i in 0..WIDTH {
+            state[i] += external_consts[round][i];
         }
-        // Apply external matrix
         apply_external_round_matrix(state);
-        // Apply S-box (x^5) to all elements
-        *state = std::array::from_fn(|i| pow5(state[i]));
+        *state = std::array::from_fn(|i| S::sbox(state[i]));
     }
 
-    // Partial rounds (only first element gets S-box)
-    for round in 0..N_PARTIAL_ROUNDS {
-        state[0] += INTERNAL_ROUND_CONSTS[round];
-        apply_internal_round_matrix(state);
-        state[0] = pow5(state[0]);
+    // Partial rounds (only first element gets the S-box)
+    let diagonal = S::internal_diagonal();
+    for round in 0..S::partial_rounds() {
+        state[0] += internal_consts[round];
+        apply_internal_round_matrix(state, diagonal);
+        state[0] = S::sbox(state[0]);
     }
 
-    // Last 4 full rounds
-    for round in 0..N_HALF_FULL_ROUNDS {
-        // Add round constants
-        for i in 0..N_STATE {
-            state[i] += EXTERNAL_ROUND_CONSTS[round + N_HALF_FULL_ROUNDS][i];
+    // Second half of full rounds
+    for round in 0..half_full_rounds {
+        for i in 0..WIDTH {
+            state[i] += external_consts[half_full_rounds + round][i];
         }
-        // Apply external matrix
         apply_external_round_matrix(state);
-        // Apply S-box (x^5) to all elements
-        *state = std::array::from_fn(|i| pow5(state[i]));
+        *state = std::array::from_fn(|i| S::sbox(state[i]));
     }
 }
 
@@ -154,9 +331,19 @@ mod tests {
 
     #[test]
     fn test_permutation() {
-        let mut state = std::array::from_fn(|i| BaseField::from_u32_unchecked(i as u32));
-        poseidon2_permutation(&mut state);
+        let mut state: [BaseField; N_STATE] = std::array::from_fn(|i| BaseField::from_u32_unchecked(i as u32));
+        poseidon2_permutation::<Width16Spec, N_STATE, RATE>(&mut state);
         // Verify state changed (permutation is not identity)
         assert_ne!(state[0], BaseField::from_u32_unchecked(0));
     }
+
+    #[test]
+    fn test_permutation_toy_spec() {
+        // Exercises the generic block-mixing/column-mixing loop in
+        // `apply_external_round_matrix` at a width other than 16.
+        let mut state: [BaseField; TOY_WIDTH] =
+            std::array::from_fn(|i| BaseField::from_u32_unchecked(i as u32));
+        poseidon2_permutation::<ToySpec, TOY_WIDTH, TOY_RATE>(&mut state);
+        assert_ne!(state[0], BaseField::from_u32_unchecked(0));
+    }
 }
@@ -1,30 +1,77 @@
 //! Poseidon2 sponge construction for hashing.
 
-use crate::poseidon2::{poseidon2_permutation, N_STATE, RATE};
+use std::marker::PhantomData;
+
 use stwo::core::fields::m31::BaseField;
 
-/// Poseidon2 sponge hasher.
+use crate::poseidon2::{poseidon2_permutation, Spec, Width16Spec, N_STATE, RATE as DEFAULT_RATE};
+
+/// Tracks which half of the duplex construction the sponge is in, holding
+/// the pending rate-sized block exactly like the halo2 duplex sponge: each
+/// rate slot is `Some` while it still holds unconsumed input (absorbing) or
+/// unread output (squeezing), and `None` once it has been used.
+#[derive(Clone)]
+enum SpongeMode<const RATE: usize> {
+    Absorbing([Option<BaseField>; RATE]),
+    Squeezing([Option<BaseField>; RATE]),
+}
+
+impl<const RATE: usize> SpongeMode<RATE> {
+    fn absorbing() -> Self {
+        SpongeMode::Absorbing(std::array::from_fn(|_| None))
+    }
+}
+
+/// Poseidon2 sponge hasher, generic over the instance `Spec`.
 ///
-/// Absorbs elements in blocks of RATE (8), automatically pads with zeros.
+/// Absorbs elements in blocks of `RATE`, automatically pads with zeros, and
+/// supports `squeeze` for multi-element duplex output so the same sponge
+/// can be used as a one-shot hash, an XOF, or a Fiat-Shamir transcript.
+/// Defaults its type parameters to the t=16 instance (`Width16Spec`), so
+/// `Poseidon2Sponge::<Width16Spec, N_STATE, RATE>::new()` is equivalent to
+/// the old non-generic `Poseidon2Sponge::new()`.
 #[derive(Clone)]
-pub struct Poseidon2Sponge {
-    state: [BaseField; N_STATE],
-    buffer: Vec<BaseField>,
+pub struct Poseidon2Sponge<S = Width16Spec, const WIDTH: usize = N_STATE, const RATE: usize = DEFAULT_RATE>
+where
+    S: Spec<WIDTH, RATE>,
+{
+    state: [BaseField; WIDTH],
+    mode: SpongeMode<RATE>,
+    _spec: PhantomData<S>,
 }
 
-impl Poseidon2Sponge {
+impl<S, const WIDTH: usize, const RATE: usize> Poseidon2Sponge<S, WIDTH, RATE>
+where
+    S: Spec<WIDTH, RATE>,
+{
     /// Creates a new hasher with zero state.
     pub fn new() -> Self {
         Self {
-            state: [BaseField::from_u32_unchecked(0); N_STATE],
-            buffer: Vec::new(),
+            state: [BaseField::from_u32_unchecked(0); WIDTH],
+            mode: SpongeMode::absorbing(),
+            _spec: PhantomData,
         }
     }
 
     /// Absorbs a single field element.
+    ///
+    /// If the sponge was squeezing, any unread squeezed output is discarded
+    /// and a fresh absorbing block is started.
     pub fn absorb(&mut self, element: BaseField) {
-        self.buffer.push(element);
-        if self.buffer.len() == RATE {
+        if matches!(self.mode, SpongeMode::Squeezing(_)) {
+            self.mode = SpongeMode::absorbing();
+        }
+
+        let SpongeMode::Absorbing(input) = &mut self.mode else {
+            unreachable!("just ensured mode is Absorbing")
+        };
+        let slot = input
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .expect("process_block starts a fresh block before it can overflow");
+        *slot = Some(element);
+
+        if input.iter().all(Option::is_some) {
             self.process_block();
         }
     }
@@ -36,50 +83,87 @@ impl Poseidon2Sponge {
         }
     }
 
+    /// Adds the pending absorbed block into the rate portion of the state
+    /// and runs the permutation, then starts a fresh absorbing block.
     fn process_block(&mut self) {
-        for i in 0..RATE {
-            self.state[i] += self.buffer[i];
+        let SpongeMode::Absorbing(input) = &self.mode else {
+            unreachable!("process_block is only called while absorbing")
+        };
+        for (i, slot) in input.iter().enumerate() {
+            if let Some(value) = slot {
+                self.state[i] += *value;
+            }
         }
-        poseidon2_permutation(&mut self.state);
-        self.buffer.clear();
+        poseidon2_permutation::<S, WIDTH, RATE>(&mut self.state);
+        self.mode = SpongeMode::absorbing();
+    }
+
+    /// Pads and permutes any pending absorbed block, if there is one.
+    fn pad_and_permute(&mut self) {
+        if let SpongeMode::Absorbing(input) = &self.mode {
+            if input.iter().any(Option::is_some) {
+                self.process_block();
+            }
+        }
+    }
+
+    /// Switches the sponge into squeezing mode, padding and permuting any
+    /// pending absorbed block first.
+    fn start_squeezing(&mut self) {
+        self.pad_and_permute();
+        self.mode = SpongeMode::Squeezing(std::array::from_fn(|i| Some(self.state[i])));
+    }
+
+    /// Squeezes `n` field elements out of the sponge, as a duplex
+    /// construction: switching from absorbing to squeezing first pads and
+    /// permutes the pending block, and re-permutes whenever the rate
+    /// portion being read from is exhausted. Calling `absorb` again after
+    /// `squeeze` discards the unread squeezed output and resumes absorbing.
+    pub fn squeeze(&mut self, n: usize) -> Vec<BaseField> {
+        let mut output = Vec::with_capacity(n);
+        while output.len() < n {
+            if matches!(self.mode, SpongeMode::Absorbing(_)) {
+                self.start_squeezing();
+            }
+
+            let SpongeMode::Squeezing(rate) = &mut self.mode else {
+                unreachable!("just ensured mode is Squeezing")
+            };
+            match rate.iter_mut().find(|slot| slot.is_some()) {
+                Some(slot) => output.push(slot.take().expect("just checked is_some")),
+                None => {
+                    poseidon2_permutation::<S, WIDTH, RATE>(&mut self.state);
+                    self.mode = SpongeMode::Squeezing(std::array::from_fn(|i| Some(self.state[i])));
+                }
+            }
+        }
+        output
     }
 
     /// Finalizes and returns the hash (first state element).
     /// Automatically pads with zeros if needed.
     pub fn finalize(mut self) -> BaseField {
-        if !self.buffer.is_empty() {
-            while self.buffer.len() < RATE {
-                self.buffer.push(BaseField::from_u32_unchecked(0));
-            }
-            self.process_block();
-        }
+        self.pad_and_permute();
         self.state[0]
     }
 
-    /// Finalizes and returns the rate portion (8 elements).
+    /// Finalizes and returns the rate portion.
     pub fn finalize_full_rate(mut self) -> [BaseField; RATE] {
-        if !self.buffer.is_empty() {
-            while self.buffer.len() < RATE {
-                self.buffer.push(BaseField::from_u32_unchecked(0));
-            }
-            self.process_block();
-        }
+        self.pad_and_permute();
         std::array::from_fn(|i| self.state[i])
     }
 
-    /// Finalizes and returns the full state (16 elements).
-    pub fn finalize_full_state(mut self) -> [BaseField; N_STATE] {
-        if !self.buffer.is_empty() {
-            while self.buffer.len() < RATE {
-                self.buffer.push(BaseField::from_u32_unchecked(0));
-            }
-            self.process_block();
-        }
+    /// Finalizes and returns the full state.
+    pub fn finalize_full_state(mut self) -> [BaseField; WIDTH] {
+        self.pad_and_permute();
         self.state
     }
 }
 
-impl Default for Poseidon2Sponge {
+impl<S, const WIDTH: usize, const RATE: usize> Default for Poseidon2Sponge<S, WIDTH, RATE>
+where
+    S: Spec<WIDTH, RATE>,
+{
     fn default() -> Self {
         Self::new()
     }
@@ -87,24 +171,24 @@ impl Default for Poseidon2Sponge {
 
 /// Hashes a slice of field elements.
 ///
-/// Automatically pads with zeros to multiples of RATE (8).
+/// Automatically pads with zeros to multiples of DEFAULT_RATE (8).
 pub fn hash(elements: &[BaseField]) -> BaseField {
-    let mut sponge = Poseidon2Sponge::new();
+    let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, DEFAULT_RATE>::new();
     sponge.absorb_many(elements);
     sponge.finalize()
 }
 
 /// Hashes multiple messages with vertical chaining.
 ///
-/// Each message is RATE (8) elements. Output state chains to next message.
-pub fn hash_messages(messages: &[[BaseField; RATE]]) -> Vec<[BaseField; N_STATE]> {
+/// Each message is DEFAULT_RATE (8) elements. Output state chains to next message.
+pub fn hash_messages(messages: &[[BaseField; DEFAULT_RATE]]) -> Vec<[BaseField; N_STATE]> {
     let mut outputs = Vec::with_capacity(messages.len());
     let mut prev_output: Option<[BaseField; N_STATE]> = None;
 
     for message in messages {
         let mut state: [BaseField; N_STATE] = if let Some(prev) = prev_output {
             std::array::from_fn(|i| {
-                if i < RATE {
+                if i < DEFAULT_RATE {
                     prev[i] + message[i]
                 } else {
                     prev[i]
@@ -112,7 +196,7 @@ pub fn hash_messages(messages: &[[BaseField; RATE]]) -> Vec<[BaseField; N_STATE]
             })
         } else {
             std::array::from_fn(|i| {
-                if i < RATE {
+                if i < DEFAULT_RATE {
                     message[i]
                 } else {
                     BaseField::from_u32_unchecked(0)
@@ -120,7 +204,7 @@ pub fn hash_messages(messages: &[[BaseField; RATE]]) -> Vec<[BaseField; N_STATE]
             })
         };
 
-        poseidon2_permutation(&mut state);
+        poseidon2_permutation::<Width16Spec, N_STATE, DEFAULT_RATE>(&mut state);
         outputs.push(state);
         prev_output = Some(state);
     }
@@ -147,7 +231,7 @@ mod tests {
 
     #[test]
     fn test_sponge_absorb() {
-        let mut sponge = Poseidon2Sponge::new();
+        let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, DEFAULT_RATE>::new();
         sponge.absorb(BaseField::from_u32_unchecked(42));
         let hash = sponge.finalize();
         assert_ne!(hash, BaseField::from_u32_unchecked(0));
@@ -194,4 +278,65 @@ mod tests {
         assert_ne!(outputs[0][0], BaseField::from_u32_unchecked(0));
         assert_ne!(outputs[1][0], BaseField::from_u32_unchecked(0));
     }
+
+    #[test]
+    fn test_squeeze_matches_finalize_full_rate() {
+        let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, DEFAULT_RATE>::new();
+        sponge.absorb_many(&[
+            BaseField::from_u32_unchecked(1),
+            BaseField::from_u32_unchecked(2),
+            BaseField::from_u32_unchecked(3),
+        ]);
+        let squeezed = sponge.squeeze(DEFAULT_RATE);
+
+        let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, DEFAULT_RATE>::new();
+        sponge.absorb_many(&[
+            BaseField::from_u32_unchecked(1),
+            BaseField::from_u32_unchecked(2),
+            BaseField::from_u32_unchecked(3),
+        ]);
+        let finalized = sponge.finalize_full_rate();
+
+        assert_eq!(squeezed, finalized.to_vec());
+    }
+
+    #[test]
+    fn test_squeeze_multiple_blocks_repermutes() {
+        let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, DEFAULT_RATE>::new();
+        sponge.absorb(BaseField::from_u32_unchecked(7));
+        let output = sponge.squeeze(DEFAULT_RATE * 2 + 1);
+
+        assert_eq!(output.len(), DEFAULT_RATE * 2 + 1);
+        // The second block must differ from the first: re-permuting should
+        // not just repeat the exhausted rate portion.
+        assert_ne!(output[0..DEFAULT_RATE], output[DEFAULT_RATE..DEFAULT_RATE * 2]);
+    }
+
+    #[test]
+    fn test_sponge_toy_spec() {
+        // Runs the sponge through the t=8/rate=4 toy instance, not just the
+        // production t=16 one, to exercise the generic code paths.
+        use crate::poseidon2::ToySpec;
+
+        let mut sponge = Poseidon2Sponge::<ToySpec, 8, 4>::new();
+        sponge.absorb_many(&[
+            BaseField::from_u32_unchecked(1),
+            BaseField::from_u32_unchecked(2),
+            BaseField::from_u32_unchecked(3),
+        ]);
+        let hash = sponge.finalize();
+        assert_ne!(hash, BaseField::from_u32_unchecked(0));
+    }
+
+    #[test]
+    fn test_absorb_after_squeeze_resets_mode() {
+        let mut sponge = Poseidon2Sponge::<Width16Spec, N_STATE, DEFAULT_RATE>::new();
+        sponge.absorb(BaseField::from_u32_unchecked(1));
+        let _ = sponge.squeeze(1);
+        // Absorbing again should discard the remaining squeezed output and
+        // start a fresh block rather than panicking or losing data.
+        sponge.absorb(BaseField::from_u32_unchecked(2));
+        let hash = sponge.finalize();
+        assert_ne!(hash, BaseField::from_u32_unchecked(0));
+    }
 }